@@ -1,4 +1,7 @@
-use crate::{recorder::Inner, MeterRecorder};
+use crate::{
+    recorder::{reclaim_stale, HistogramAggregation, Inner},
+    MeterRecorder,
+};
 use metrics::{Key, SetRecorderError};
 use metrics_util::{Handle, MetricKindMask, Recency, Registry, Tracked};
 use opentelemetry::{
@@ -7,7 +10,16 @@ use opentelemetry::{
 };
 use parking_lot::RwLock;
 use quanta::Clock;
-use std::{collections::HashMap, sync::Arc, time::Duration};
+use std::{
+    collections::HashMap,
+    sync::{Arc, Weak},
+    thread,
+    time::Duration,
+};
+
+fn default_quantiles() -> Vec<f64> {
+    vec![0.5, 0.9, 0.99]
+}
 
 /// Builder for creating and installing an OpenTelemetry recorder/exporter.
 pub struct OtelBuilder<M: MeterProvider> {
@@ -15,6 +27,9 @@ pub struct OtelBuilder<M: MeterProvider> {
     mask: MetricKindMask,
     clock: Clock,
     provider: M,
+    quantiles: Vec<f64>,
+    histogram_aggregation: HistogramAggregation,
+    typed_labels: bool,
 }
 
 impl OtelBuilder<GlobalMeterProvider> {
@@ -24,6 +39,9 @@ impl OtelBuilder<GlobalMeterProvider> {
             mask: MetricKindMask::ALL,
             clock: Clock::new(),
             provider: meter_provider(),
+            quantiles: default_quantiles(),
+            histogram_aggregation: HistogramAggregation::Summary,
+            typed_labels: false,
         }
     }
 }
@@ -35,6 +53,9 @@ impl<M: MeterProvider> OtelBuilder<M> {
             mask: MetricKindMask::ALL,
             clock: Clock::new(),
             provider: meter_provider,
+            quantiles: default_quantiles(),
+            histogram_aggregation: HistogramAggregation::Summary,
+            typed_labels: false,
         }
     }
 
@@ -53,19 +74,62 @@ impl<M: MeterProvider> OtelBuilder<M> {
         self
     }
 
+    /// Sets the quantiles reported for each histogram, as a fraction in `[0.0, 1.0]`.
+    ///
+    /// Defaults to `[0.5, 0.9, 0.99]`.
+    pub fn quantiles(mut self, quantiles: &[f64]) -> Self {
+        self.quantiles = quantiles.to_vec();
+        self
+    }
+
+    /// Selects how histograms are aggregated and reported. Defaults to
+    /// `HistogramAggregation::Summary`.
+    pub fn histogram_aggregation(mut self, aggregation: HistogramAggregation) -> Self {
+        self.histogram_aggregation = aggregation;
+        self
+    }
+
+    /// Infers `Bool`/`I64`/`F64` label values instead of reporting every label as a string.
+    /// Defaults to `false`, since the parsing attempt costs something on every collection.
+    pub fn typed_labels(mut self, typed_labels: bool) -> Self {
+        self.typed_labels = typed_labels;
+        self
+    }
+
     pub fn build(self) -> MeterRecorder {
         let meter = self
             .provider
             .meter("github.com/vibhavp/metrics-rs-opentelemetry", Some("0.1.0"));
+        let idle_timeout = self.idle_timeout;
 
         let inner = Arc::new(Inner {
             meter,
-            recency: Recency::new(self.clock, self.mask, self.idle_timeout),
+            recency: Recency::new(self.clock, self.mask, idle_timeout),
             registry: Registry::<Key, Handle, Tracked<Handle>>::tracked(),
             descriptions: RwLock::new(HashMap::new()),
             units: RwLock::new(HashMap::new()),
+            distributions: RwLock::new(HashMap::new()),
+            quantiles: self.quantiles,
+            histogram_aggregation: self.histogram_aggregation,
+            exponential_histograms: RwLock::new(HashMap::new()),
+            typed_labels: self.typed_labels,
         });
 
+        // Without an idle timeout nothing ever goes stale, so there's nothing to reclaim.
+        // Hold only a Weak reference so the thread doesn't keep `Inner` (and its handles)
+        // alive forever — it exits on its own once the last strong reference is gone (the
+        // recorder and every observer callback registered with the meter each hold one).
+        if let Some(idle_timeout) = idle_timeout {
+            let weak_inner: Weak<Inner> = Arc::downgrade(&inner);
+            thread::spawn(move || loop {
+                thread::sleep(idle_timeout);
+                match weak_inner.upgrade() {
+                    Some(inner) => reclaim_stale(&inner),
+                    None => break,
+                }
+            });
+        }
+
         MeterRecorder { inner }
     }
 
@@ -0,0 +1,146 @@
+use std::collections::HashMap;
+
+/// A base-2 exponential histogram, as described by the OTLP exponential histogram
+/// data point: values are bucketed by `index = ceil(log2(value) / log2(base))`,
+/// where `base = 2^(2^-scale)`. Positive and negative values are tracked in
+/// separate bucket maps, with zero tracked separately from both.
+pub(crate) struct ExponentialHistogram {
+    scale: i8,
+    positive: HashMap<i32, u64>,
+    negative: HashMap<i32, u64>,
+    zero_count: u64,
+    sum: f64,
+    count: u64,
+}
+
+impl ExponentialHistogram {
+    pub fn new(scale: i8) -> Self {
+        ExponentialHistogram {
+            scale,
+            positive: HashMap::new(),
+            negative: HashMap::new(),
+            zero_count: 0,
+            sum: 0.0,
+            count: 0,
+        }
+    }
+
+    pub fn add(&mut self, value: f64) {
+        if !value.is_finite() {
+            return;
+        }
+
+        self.sum += value;
+        self.count += 1;
+
+        if value == 0.0 {
+            self.zero_count += 1;
+            return;
+        }
+
+        let index = bucket_index(value.abs(), self.scale);
+        let buckets = if value > 0.0 {
+            &mut self.positive
+        } else {
+            &mut self.negative
+        };
+        *buckets.entry(index).or_insert(0) += 1;
+    }
+
+    pub fn scale(&self) -> i8 {
+        self.scale
+    }
+
+    pub fn sum(&self) -> f64 {
+        self.sum
+    }
+
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    pub fn zero_count(&self) -> u64 {
+        self.zero_count
+    }
+
+    pub fn positive_buckets(&self) -> impl Iterator<Item = (i32, u64)> + '_ {
+        self.positive.iter().map(|(&index, &count)| (index, count))
+    }
+
+    pub fn negative_buckets(&self) -> impl Iterator<Item = (i32, u64)> + '_ {
+        self.negative.iter().map(|(&index, &count)| (index, count))
+    }
+}
+
+/// Maps a positive value to its exponential bucket index at the given scale.
+fn bucket_index(value: f64, scale: i8) -> i32 {
+    let scale_factor = 2f64.powi(scale as i32);
+    (value.log2() * scale_factor).ceil() as i32
+}
+
+/// Returns the upper bound of the bucket at `index` for the given scale, i.e. `base^index`.
+pub(crate) fn bucket_upper_bound(index: i32, scale: i8) -> f64 {
+    let scale_factor = 2f64.powi(-(scale as i32));
+    2f64.powf(index as f64 * scale_factor)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bucket_upper_bound_is_power_of_two_at_scale_zero() {
+        // At scale 0, base == 2, so bucket `n` tops out at exactly 2^n.
+        assert_eq!(bucket_upper_bound(0, 0), 1.0);
+        assert_eq!(bucket_upper_bound(1, 0), 2.0);
+        assert_eq!(bucket_upper_bound(4, 0), 16.0);
+    }
+
+    #[test]
+    fn bucket_index_places_value_at_or_below_its_upper_bound() {
+        for scale in [-2, 0, 2, 4] {
+            for value in [0.001, 0.5, 1.0, 2.0, 17.3, 1000.0] {
+                let index = bucket_index(value, scale);
+                assert!(
+                    value <= bucket_upper_bound(index, scale) + f64::EPSILON,
+                    "value {value} should fall within bucket {index} at scale {scale}"
+                );
+                assert!(
+                    value > bucket_upper_bound(index - 1, scale) - f64::EPSILON,
+                    "value {value} should not fit in the previous bucket {} at scale {scale}",
+                    index - 1
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn add_tracks_zero_positive_and_negative_separately() {
+        let mut histogram = ExponentialHistogram::new(2);
+        histogram.add(0.0);
+        histogram.add(1.0);
+        histogram.add(-1.0);
+
+        assert_eq!(histogram.zero_count(), 1);
+        assert_eq!(histogram.count(), 3);
+        assert_eq!(histogram.sum(), 0.0);
+        assert_eq!(histogram.positive_buckets().map(|(_, c)| c).sum::<u64>(), 1);
+        assert_eq!(histogram.negative_buckets().map(|(_, c)| c).sum::<u64>(), 1);
+    }
+
+    #[test]
+    fn add_is_stable_across_cycles() {
+        let mut histogram = ExponentialHistogram::new(2);
+        histogram.add(4.0);
+        let first_index = histogram.positive_buckets().next().unwrap().0;
+
+        histogram.add(4.0);
+        let second_index = histogram
+            .positive_buckets()
+            .find(|(_, count)| *count == 2)
+            .expect("repeated value should accumulate in the same bucket")
+            .0;
+
+        assert_eq!(first_index, second_index);
+    }
+}
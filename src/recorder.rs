@@ -1,29 +1,166 @@
+use crate::exponential::{bucket_upper_bound, ExponentialHistogram};
 use core::slice::Iter;
 use metrics::{GaugeValue, Key, Label, Recorder, Unit};
-use metrics_util::{Handle, MetricKind, Recency, Registry, Tracked};
-use opentelemetry::{metrics::Meter, KeyValue, Value};
+use metrics_util::{Handle, MetricKind, Recency, Registry, Summary, Tracked};
+use opentelemetry::{
+    metrics::{Meter, Unit as OtelUnit},
+    KeyValue, Value,
+};
 use parking_lot::RwLock;
 use std::{borrow::Cow, collections::HashMap, sync::Arc};
 
-fn labels_to_keyvalue(labels: Iter<'_, Label>) -> Vec<KeyValue> {
+/// Selects how `register_histogram` aggregates and reports buffered samples.
+pub enum HistogramAggregation {
+    /// Summarize samples with a relative-error quantile sketch, reporting the
+    /// configured quantiles plus `_sum`/`_count` companion series.
+    Summary,
+    /// Bucket samples into a base-2 exponential histogram at the given scale
+    /// (e.g. scale 2 ≈ 17% relative error), reporting per-bucket counts.
+    Exponential { scale: i8 },
+}
+
+/// Converts a label's string value into an OpenTelemetry `Value`, inferring `Bool`/`I64`/`F64`
+/// when `typed` is set so backends can filter and aggregate on the native type instead of
+/// treating every attribute as a string.
+fn label_value(value: &str, typed: bool) -> Value {
+    if typed {
+        if let Ok(value) = value.parse::<bool>() {
+            return Value::Bool(value);
+        }
+        if let Ok(value) = value.parse::<i64>() {
+            return Value::I64(value);
+        }
+        if let Ok(value) = value.parse::<f64>() {
+            return Value::F64(value);
+        }
+    }
+
+    Value::String(Cow::Owned(value.to_string()))
+}
+
+fn labels_to_keyvalue(labels: Iter<'_, Label>, typed: bool) -> Vec<KeyValue> {
     let mut kv = Vec::new();
 
     for label in labels {
         kv.push(KeyValue::new(
             label.key().to_string(),
-            Value::String(Cow::Owned(label.value().to_string())),
+            label_value(label.value(), typed),
         ))
     }
 
     kv
 }
 
+/// A `metrics_util::Summary` (a relative-error DDSketch) paired with an exact running sum,
+/// since the sketch itself doesn't track one.
+pub(crate) struct HistogramSummary {
+    summary: Summary,
+    sum: f64,
+}
+
+impl HistogramSummary {
+    fn new() -> Self {
+        HistogramSummary {
+            summary: Summary::with_defaults(),
+            sum: 0.0,
+        }
+    }
+
+    fn add(&mut self, value: f64) {
+        if !value.is_finite() {
+            return;
+        }
+        self.summary.add(value);
+        self.sum += value;
+    }
+
+    fn quantile(&self, quantile: f64) -> Option<f64> {
+        self.summary.quantile(quantile)
+    }
+
+    fn sum(&self) -> f64 {
+        self.sum
+    }
+
+    fn count(&self) -> usize {
+        self.summary.count()
+    }
+}
+
+/// Maps a `metrics::Unit` to its UCUM string representation, as expected by
+/// OpenTelemetry instruments (see the `Unit` section of the OTLP metrics spec).
+fn unit_to_ucum(unit: &Unit) -> &'static str {
+    match unit {
+        Unit::Count => "{count}",
+        Unit::Percent => "%",
+        Unit::Seconds => "s",
+        Unit::Milliseconds => "ms",
+        Unit::Microseconds => "us",
+        Unit::Nanoseconds => "ns",
+        Unit::Tebibytes => "TiBy",
+        Unit::Gigibytes => "GiBy",
+        Unit::Mebibytes => "MiBy",
+        Unit::Kibibytes => "KiBy",
+        Unit::Bytes => "By",
+        Unit::TerabitsPerSecond => "Tbit/s",
+        Unit::GigabitsPerSecond => "Gbit/s",
+        Unit::MegabitsPerSecond => "Mbit/s",
+        Unit::KilobitsPerSecond => "kbit/s",
+        Unit::BitsPerSecond => "bit/s",
+        Unit::CountPerSecond => "{count}/s",
+    }
+}
+
 pub(crate) struct Inner {
     pub meter: Meter,
     pub recency: Recency<Key>,
     pub registry: Registry<Key, Handle, Tracked<Handle>>,
     pub descriptions: RwLock<HashMap<String, &'static str>>,
     pub units: RwLock<HashMap<String, Unit>>,
+    /// Per-key DDSketch summaries backing the quantiles emitted by `register_histogram`.
+    pub distributions: RwLock<HashMap<Key, HistogramSummary>>,
+    /// Quantiles reported for each histogram on every collection cycle that drained new samples.
+    pub quantiles: Vec<f64>,
+    /// How `register_histogram` aggregates buffered samples.
+    pub histogram_aggregation: HistogramAggregation,
+    /// Per-key exponential histograms, populated when `histogram_aggregation` is `Exponential`.
+    pub exponential_histograms: RwLock<HashMap<Key, ExponentialHistogram>>,
+    /// Whether label values are parsed into `Bool`/`I64`/`F64` instead of always `String`.
+    pub typed_labels: bool,
+}
+
+/// Evicts keys that `recency` considers idle (past its configured `idle_timeout`)
+/// from the description/unit/distribution bookkeeping maps, so churned label sets
+/// (e.g. high-cardinality per-request labels) don't accumulate dead state forever.
+/// Histogram sketches are dropped for every key whose handle is gone from `registry`;
+/// description and unit entries are removed only for keys this pass itself finds idle.
+///
+/// `Recency::should_store` already drops the handle itself from `registry` once a
+/// key goes idle; the observer callbacks re-check the registry on every collection,
+/// so a key dropped here simply stops being observed on the next cycle.
+pub(crate) fn reclaim_stale(inner: &Inner) {
+    for ((kind, key), (generation, _)) in inner.registry.get_handles().iter() {
+        if !inner
+            .recency
+            .should_store(*kind, key, generation.clone(), &inner.registry)
+        {
+            inner.descriptions.write().remove(key.name());
+            inner.units.write().remove(key.name());
+            inner.distributions.write().remove(key);
+            inner.exponential_histograms.write().remove(key);
+        }
+    }
+
+    // An observer callback's own `should_store` call may already have dropped an idle key's
+    // handle from `registry`, so the loop above never sees it: drop the sketch of every
+    // histogram key without a handle. Both locks are held before the snapshot is taken, so
+    // every sketch in them was created while its handle existed.
+    let mut distributions = inner.distributions.write();
+    let mut exponential_histograms = inner.exponential_histograms.write();
+    let handles = inner.registry.get_handles();
+    let has_handle = |key: &Key| handles.contains_key(&(MetricKind::Histogram, key.clone()));
+    distributions.retain(|key, _| has_handle(key));
+    exponential_histograms.retain(|key, _| has_handle(key));
 }
 
 pub struct MeterRecorder {
@@ -62,25 +199,31 @@ impl Recorder for MeterRecorder {
 
         let inner = self.inner.clone();
         let key_1 = key.clone();
-        self.inner
-            .meter
-            .u64_sum_observer(key.name(), move |observer| {
-                let handles = inner.registry.get_handles();
-                let metric = handles.get(&(MetricKind::Counter, key_1.clone()));
+        let mut builder = self.inner.meter.u64_sum_observer(key.name(), move |observer| {
+            let handles = inner.registry.get_handles();
+            let metric = handles.get(&(MetricKind::Counter, key_1.clone()));
 
-                if let Some(metric) = metric {
-                    if inner.recency.should_store(
-                        MetricKind::Counter,
-                        &key_1,
-                        metric.0.clone(),
-                        &inner.registry,
-                    ) {
-                        observer
-                            .observe(metric.1.read_counter(), &labels_to_keyvalue(key_1.labels()));
-                    }
+            if let Some(metric) = metric {
+                if inner.recency.should_store(
+                    MetricKind::Counter,
+                    &key_1,
+                    metric.0.clone(),
+                    &inner.registry,
+                ) {
+                    observer.observe(
+                        metric.1.read_counter(),
+                        &labels_to_keyvalue(key_1.labels(), inner.typed_labels),
+                    );
                 }
-            })
-            .init();
+            }
+        });
+        if let Some(description) = self.inner.descriptions.read().get(key.name()) {
+            builder = builder.with_description(description.to_string());
+        }
+        if let Some(unit) = self.inner.units.read().get(key.name()) {
+            builder = builder.with_unit(OtelUnit::new(unit_to_ucum(unit)));
+        }
+        builder.init();
     }
 
     fn register_gauge(&self, key: &Key, unit: Option<Unit>, description: Option<&'static str>) {
@@ -91,7 +234,8 @@ impl Recorder for MeterRecorder {
 
         let inner = self.inner.clone();
         let key_1 = key.clone();
-        self.inner
+        let mut builder = self
+            .inner
             .meter
             .f64_up_down_sum_observer(key.name(), move |observer| {
                 let handles = inner.registry.get_handles();
@@ -104,11 +248,20 @@ impl Recorder for MeterRecorder {
                         metric.0.clone(),
                         &inner.registry,
                     ) {
-                        observer
-                            .observe(metric.1.read_gauge(), &labels_to_keyvalue(key_1.labels()));
+                        observer.observe(
+                            metric.1.read_gauge(),
+                            &labels_to_keyvalue(key_1.labels(), inner.typed_labels),
+                        );
                     }
                 }
             });
+        if let Some(description) = self.inner.descriptions.read().get(key.name()) {
+            builder = builder.with_description(description.to_string());
+        }
+        if let Some(unit) = self.inner.units.read().get(key.name()) {
+            builder = builder.with_unit(OtelUnit::new(unit_to_ucum(unit)));
+        }
+        builder.init();
     }
 
     fn register_histogram(&self, key: &Key, unit: Option<Unit>, description: Option<&'static str>) {
@@ -119,28 +272,163 @@ impl Recorder for MeterRecorder {
 
         let inner = self.inner.clone();
         let key_1 = key.clone();
-        self.inner
+        let mut builder = self
+            .inner
             .meter
             .f64_value_observer(key.name(), move |observer| {
                 let handles = inner.registry.get_handles();
                 let metric = handles.get(&(MetricKind::Histogram, key_1.clone()));
 
-                if let Some(metric) = metric {
-                    if inner.recency.should_store(
-                        MetricKind::Histogram,
-                        &key_1,
-                        metric.0.clone(),
-                        &inner.registry,
-                    ) {
-                        let key_values = &labels_to_keyvalue(key_1.labels());
+                let metric = match metric {
+                    Some(metric) => metric,
+                    None => return,
+                };
+
+                if !inner.recency.should_store(
+                    MetricKind::Histogram,
+                    &key_1,
+                    metric.0.clone(),
+                    &inner.registry,
+                ) {
+                    return;
+                }
+
+                let key_values = labels_to_keyvalue(key_1.labels(), inner.typed_labels);
+
+                match &inner.histogram_aggregation {
+                    HistogramAggregation::Summary => {
+                        let mut drained_any = false;
                         metric.1.read_histogram_with_clear(|values| {
+                            if values.is_empty() {
+                                return;
+                            }
+                            drained_any = true;
+                            let mut distributions = inner.distributions.write();
+                            let summary = distributions
+                                .entry(key_1.clone())
+                                .or_insert_with(HistogramSummary::new);
                             for value in values.iter() {
-                                observer.observe(*value, key_values);
+                                summary.add(*value);
                             }
                         });
+                        if !drained_any {
+                            return;
+                        }
+
+                        let distributions = inner.distributions.read();
+                        let summary = match distributions.get(&key_1) {
+                            Some(summary) => summary,
+                            None => return,
+                        };
+                        for &quantile in &inner.quantiles {
+                            if let Some(value) = summary.quantile(quantile) {
+                                let mut labels = key_values.clone();
+                                labels.push(KeyValue::new("quantile", quantile));
+                                observer.observe(value, &labels);
+                            }
+                        }
+                    }
+                    HistogramAggregation::Exponential { scale } => {
+                        let scale = *scale;
+                        let mut drained_any = false;
+                        metric.1.read_histogram_with_clear(|values| {
+                            if values.is_empty() {
+                                return;
+                            }
+                            drained_any = true;
+                            let mut histograms = inner.exponential_histograms.write();
+                            let histogram = histograms
+                                .entry(key_1.clone())
+                                .or_insert_with(|| ExponentialHistogram::new(scale));
+                            for value in values.iter() {
+                                histogram.add(*value);
+                            }
+                        });
+                        if !drained_any {
+                            return;
+                        }
+
+                        let histograms = inner.exponential_histograms.read();
+                        let histogram = match histograms.get(&key_1) {
+                            Some(histogram) => histogram,
+                            None => return,
+                        };
+
+                        if histogram.zero_count() > 0 {
+                            let mut labels = key_values.clone();
+                            labels.push(KeyValue::new("le", "0"));
+                            observer.observe(histogram.zero_count() as f64, &labels);
+                        }
+                        for (index, count) in histogram.negative_buckets() {
+                            let mut labels = key_values.clone();
+                            labels.push(KeyValue::new(
+                                "le",
+                                format!("-{}", bucket_upper_bound(index, histogram.scale())),
+                            ));
+                            observer.observe(count as f64, &labels);
+                        }
+                        for (index, count) in histogram.positive_buckets() {
+                            let mut labels = key_values.clone();
+                            labels.push(KeyValue::new(
+                                "le",
+                                bucket_upper_bound(index, histogram.scale()).to_string(),
+                            ));
+                            observer.observe(count as f64, &labels);
+                        }
                     }
                 }
             });
+        if let Some(description) = self.inner.descriptions.read().get(key.name()) {
+            builder = builder.with_description(description.to_string());
+        }
+        if let Some(unit) = self.inner.units.read().get(key.name()) {
+            builder = builder.with_unit(OtelUnit::new(unit_to_ucum(unit)));
+        }
+        builder.init();
+
+        let inner = self.inner.clone();
+        let key_1 = key.clone();
+        self.inner
+            .meter
+            .f64_value_observer(format!("{}_sum", key.name()), move |observer| {
+                let sum = match &inner.histogram_aggregation {
+                    HistogramAggregation::Summary => {
+                        inner.distributions.read().get(&key_1).map(HistogramSummary::sum)
+                    }
+                    HistogramAggregation::Exponential { .. } => inner
+                        .exponential_histograms
+                        .read()
+                        .get(&key_1)
+                        .map(ExponentialHistogram::sum),
+                };
+                if let Some(sum) = sum {
+                    observer.observe(sum, &labels_to_keyvalue(key_1.labels(), inner.typed_labels));
+                }
+            })
+            .init();
+
+        let inner = self.inner.clone();
+        let key_1 = key.clone();
+        self.inner
+            .meter
+            .f64_value_observer(format!("{}_count", key.name()), move |observer| {
+                let count = match &inner.histogram_aggregation {
+                    HistogramAggregation::Summary => inner
+                        .distributions
+                        .read()
+                        .get(&key_1)
+                        .map(|summary| summary.count() as f64),
+                    HistogramAggregation::Exponential { .. } => inner
+                        .exponential_histograms
+                        .read()
+                        .get(&key_1)
+                        .map(|histogram| histogram.count() as f64),
+                };
+                if let Some(count) = count {
+                    observer.observe(count, &labels_to_keyvalue(key_1.labels(), inner.typed_labels));
+                }
+            })
+            .init();
     }
 
     fn increment_counter(&self, key: &Key, value: u64) {
@@ -170,3 +458,130 @@ impl Recorder for MeterRecorder {
         );
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unit_to_ucum_maps_documented_variants() {
+        assert_eq!(unit_to_ucum(&Unit::Bytes), "By");
+        assert_eq!(unit_to_ucum(&Unit::Nanoseconds), "ns");
+        assert_eq!(unit_to_ucum(&Unit::Seconds), "s");
+        assert_eq!(unit_to_ucum(&Unit::Percent), "%");
+        assert_eq!(unit_to_ucum(&Unit::Count), "{count}");
+        assert_eq!(unit_to_ucum(&Unit::Gigibytes), "GiBy");
+    }
+
+    #[test]
+    fn label_value_keeps_strings_untyped_by_default() {
+        assert_eq!(
+            label_value("true", false),
+            Value::String(Cow::Borrowed("true"))
+        );
+        assert_eq!(label_value("42", false), Value::String(Cow::Borrowed("42")));
+    }
+
+    #[test]
+    fn label_value_prefers_bool_over_numeric_when_typed() {
+        assert_eq!(label_value("true", true), Value::Bool(true));
+        assert_eq!(label_value("false", true), Value::Bool(false));
+    }
+
+    #[test]
+    fn label_value_prefers_i64_over_f64_when_typed() {
+        assert_eq!(label_value("42", true), Value::I64(42));
+        assert_eq!(label_value("-7", true), Value::I64(-7));
+    }
+
+    #[test]
+    fn label_value_falls_back_to_f64_then_string_when_typed() {
+        assert_eq!(label_value("4.2", true), Value::F64(4.2));
+        assert_eq!(
+            label_value("not-a-number", true),
+            Value::String(Cow::Borrowed("not-a-number"))
+        );
+    }
+
+    #[test]
+    fn histogram_summary_tracks_sum_count_and_quantiles() {
+        let mut summary = HistogramSummary::new();
+        for value in [1.0, 2.0, 3.0, 4.0, 5.0] {
+            summary.add(value);
+        }
+
+        assert_eq!(summary.count(), 5);
+        assert_eq!(summary.sum(), 15.0);
+        // `Summary` is a DDSketch, so quantiles are approximate, not exact order
+        // statistics — just check it lands near the middle of the sample range.
+        let median = summary.quantile(0.5).expect("quantile should be defined");
+        assert!((median - 2.0).abs() < 0.1, "median was {median}");
+    }
+
+    #[test]
+    fn histogram_summary_ignores_non_finite_samples() {
+        let mut summary = HistogramSummary::new();
+        summary.add(1.0);
+        summary.add(f64::NAN);
+        summary.add(f64::INFINITY);
+
+        assert_eq!(summary.count(), 1);
+        assert_eq!(summary.sum(), 1.0);
+    }
+
+    #[test]
+    fn reclaim_stale_evicts_a_key_once_it_goes_idle() {
+        use metrics_util::MetricKindMask;
+        use opentelemetry::metrics::{noop::NoopMeterProvider, MeterProvider};
+        use quanta::Clock;
+        use std::time::Duration;
+
+        let (clock, mock) = Clock::mock();
+        let key = Key::from_name("test_metric");
+
+        let inner = Inner {
+            meter: NoopMeterProvider::new().meter("test", None),
+            recency: Recency::new(clock, MetricKindMask::ALL, Some(Duration::from_secs(60))),
+            registry: Registry::<Key, Handle, Tracked<Handle>>::tracked(),
+            descriptions: RwLock::new(HashMap::from([(
+                key.name().to_string(),
+                "a description",
+            )])),
+            units: RwLock::new(HashMap::from([(key.name().to_string(), Unit::Count)])),
+            distributions: RwLock::new(HashMap::new()),
+            quantiles: vec![0.5],
+            histogram_aggregation: HistogramAggregation::Summary,
+            exponential_histograms: RwLock::new(HashMap::new()),
+            typed_labels: false,
+        };
+        inner
+            .distributions
+            .write()
+            .insert(key.clone(), HistogramSummary::new());
+
+        inner
+            .registry
+            .op(MetricKind::Counter, &key, |_| {}, Handle::counter);
+
+        // Establish the initial generation so `should_store` has a baseline to compare against.
+        let handles = inner.registry.get_handles();
+        let (generation, _) = handles
+            .get(&(MetricKind::Counter, key.clone()))
+            .expect("handle was just inserted");
+        assert!(inner.recency.should_store(
+            MetricKind::Counter,
+            &key,
+            generation.clone(),
+            &inner.registry,
+        ));
+
+        // Advance the mock clock well past the idle timeout without the key being touched again.
+        mock.increment(Duration::from_secs(120));
+
+        reclaim_stale(&inner);
+
+        assert!(!inner.descriptions.read().contains_key(key.name()));
+        assert!(!inner.units.read().contains_key(key.name()));
+        assert!(!inner.distributions.read().contains_key(&key));
+    }
+}